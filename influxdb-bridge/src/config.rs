@@ -0,0 +1,155 @@
+use crate::settings::{parse_station_entry, Settings};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use weather_underground as wu;
+
+/// Errors loading or reloading a TOML config file.
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    Invalid(String),
+}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(err: toml::de::Error) -> Self {
+        Self::Parse(err)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RawSettings {
+    stations: Vec<String>,
+    timeout_ms: Option<u64>,
+    interval_ms: Option<u64>,
+    unit: Option<String>,
+    influxdb_host: Option<String>,
+    influxdb_username: Option<String>,
+    influxdb_password: Option<String>,
+    influxdb_database: Option<String>,
+}
+
+impl TryFrom<RawSettings> for Settings {
+    type Error = ConfigError;
+
+    fn try_from(raw: RawSettings) -> Result<Self, Self::Error> {
+        if raw.stations.is_empty() {
+            return Err(ConfigError::Invalid("stations shouldn't be empty".into()));
+        }
+
+        let mut stations = Vec::new();
+        let mut station_intervals = HashMap::new();
+        for entry in raw.stations {
+            let (station_id, interval) =
+                parse_station_entry(&entry).map_err(ConfigError::Invalid)?;
+            if let Some(interval) = interval {
+                station_intervals.insert(station_id.clone(), interval);
+            }
+            stations.push(station_id);
+        }
+
+        let unit = match raw.unit.as_deref() {
+            Some("e") | Some("imperial") => wu::Unit::Imperial,
+            Some("m") | Some("metric") | None => wu::Unit::Metric,
+            Some(other) => return Err(ConfigError::Invalid(format!("invalid unit {}", other))),
+        };
+
+        Ok(Settings {
+            stations,
+            station_intervals,
+            timeout: Duration::from_millis(raw.timeout_ms.unwrap_or(10_000)),
+            interval: Duration::from_millis(raw.interval_ms.unwrap_or(60_000)),
+            unit,
+            influxdb_host: raw
+                .influxdb_host
+                .unwrap_or_else(|| "http://localhost:8086".into()),
+            influxdb_username: raw.influxdb_username.unwrap_or_else(|| "username".into()),
+            influxdb_password: raw.influxdb_password.unwrap_or_else(|| "password".into()),
+            influxdb_database: raw.influxdb_database.unwrap_or_else(|| "default".into()),
+        })
+    }
+}
+
+/// Load settings from a TOML config file.
+pub fn load(path: &Path) -> Result<Settings, ConfigError> {
+    let contents = std::fs::read_to_string(path)?;
+    let raw: RawSettings = toml::from_str(&contents)?;
+    Settings::try_from(raw)
+}
+
+/// Reload `path` into `current` whenever the process receives SIGHUP.
+pub async fn watch(path: PathBuf, current: Arc<RwLock<Settings>>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(value) => value,
+        Err(err) => {
+            error!("unable to listen for SIGHUP: {:?}", err);
+            return;
+        }
+    };
+
+    loop {
+        hangup.recv().await;
+        info!("reloading config from {:?}", path);
+        match load(&path) {
+            Ok(settings) => {
+                *current.write().await = settings;
+                info!("config reloaded");
+            }
+            Err(err) => error!("unable to reload config: {:?}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw_with(stations: Vec<&str>) -> RawSettings {
+        RawSettings {
+            stations: stations.into_iter().map(String::from).collect(),
+            timeout_ms: None,
+            interval_ms: None,
+            unit: None,
+            influxdb_host: None,
+            influxdb_username: None,
+            influxdb_password: None,
+            influxdb_database: None,
+        }
+    }
+
+    #[test]
+    fn try_from_rejects_empty_stations() {
+        let err = Settings::try_from(raw_with(vec![])).unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid(_)));
+    }
+
+    #[test]
+    fn try_from_rejects_bad_interval() {
+        let err = Settings::try_from(raw_with(vec!["KSEA:notanumber"])).unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid(_)));
+    }
+
+    #[test]
+    fn try_from_parses_stations_and_intervals() {
+        let settings = Settings::try_from(raw_with(vec!["KSEA", "KPDX:30000"])).unwrap();
+        assert_eq!(settings.stations, vec!["KSEA", "KPDX"]);
+        assert_eq!(
+            settings.station_intervals.get("KPDX"),
+            Some(&Duration::from_millis(30_000))
+        );
+        assert!(!settings.station_intervals.contains_key("KSEA"));
+    }
+}
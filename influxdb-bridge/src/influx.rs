@@ -17,43 +17,137 @@ macro_rules! add_float {
 }
 
 pub async fn publish(settings: &Settings, station_id: &str, response: &wu::ObservationResponse) {
+    if let Some(observations) = response.observations.as_ref() {
+        publish_observations(settings, station_id, observations).await;
+    }
+}
+
+/// Publish a historical observation series.
+pub async fn publish_history(settings: &Settings, station_id: &str, response: &wu::HistoryResponse) {
+    publish_observations(settings, station_id, &response.observations).await;
+}
+
+fn build_point(settings: &Settings, name: &str, obs: &wu::Observation) -> influxdb::Point {
+    let point = influxdb::Point::new(name);
+    let point = point
+        .add_timestamp(obs.epoch as i64)
+        .add_tag("unit", influxdb::Value::String(settings.unit.as_str().into()))
+        .add_tag("country", influxdb::Value::String(obs.country.clone()))
+        .add_tag("neighborhood", influxdb::Value::String(obs.neighborhood.clone()))
+        .add_tag("lat", influxdb::Value::Float(obs.lat))
+        .add_tag("lng", influxdb::Value::Float(obs.lon));
+    let point = match obs.condition.as_ref() {
+        Some(condition) => {
+            let point = match condition.main.as_ref() {
+                Some(main) => point.add_tag("condition_main", influxdb::Value::String(main.clone())),
+                None => point,
+            };
+            let point = match condition.description.as_ref() {
+                Some(description) => point.add_tag(
+                    "condition_description",
+                    influxdb::Value::String(description.clone()),
+                ),
+                None => point,
+            };
+            let point = match condition.icon.as_ref() {
+                Some(icon) => point.add_tag("condition_icon", influxdb::Value::String(icon.clone())),
+                None => point,
+            };
+            match condition.condition_code {
+                Some(code) => point.add_tag("condition_code", influxdb::Value::Integer(code)),
+                None => point,
+            }
+        }
+        None => point,
+    };
+    let point = add_float!(obs, point, humidity);
+    let point = add_float!(obs, point, solar_radiation);
+    let point = add_float!(obs, point, uv);
+    let point = add_float!(obs, point, winddir, "wind_dir");
+    match obs.values() {
+        Some(values) => {
+            let point = add_float!(values, point, dewpt);
+            let point = add_float!(values, point, elev);
+            let point = add_float!(values, point, heat_index);
+            let point = add_float!(values, point, precip_rate);
+            let point = add_float!(values, point, precip_total);
+            let point = add_float!(values, point, pressure);
+            let point = add_float!(values, point, temp);
+            let point = add_float!(values, point, wind_chill);
+            let point = add_float!(values, point, wind_gust);
+            add_float!(values, point, wind_speed)
+        }
+        None => point,
+    }
+}
+
+async fn publish_observations(settings: &Settings, station_id: &str, observations: &[wu::Observation]) {
     debug!("publishing for station {}", station_id);
     let url = reqwest::Url::from_str(settings.influxdb_host.as_str()).unwrap();
     let client = influxdb::Client::new(url, settings.influxdb_database.clone())
         .set_authentication(settings.influxdb_username.as_str(), settings.influxdb_password.as_str());
     let name = format!("weather-underground_{}", station_id);
-    if let Some(observations) = response.observations.as_ref() {
-        for obs in observations.iter() {
-            let point = influxdb::Point::new(name.as_str());
-            let point = point
-                .add_tag("unit", influxdb::Value::String(settings.unit.as_str().into()))
-                .add_tag("country", influxdb::Value::String(obs.country.clone()))
-                .add_tag("neighborhood", influxdb::Value::String(obs.neighborhood.clone()))
-                .add_tag("lat", influxdb::Value::Float(obs.lat))
-                .add_tag("lng", influxdb::Value::Float(obs.lon));
-            let point = add_float!(obs, point, humidity);
-            let point = add_float!(obs, point, solar_radiation);
-            let point = add_float!(obs, point, uv);
-            let point = add_float!(obs, point, winddir, "wind_dir");
-            let point = match obs.values() {
-                Some(values) => {
-                    let point = add_float!(values, point, dewpt);
-                    let point = add_float!(values, point, elev);
-                    let point = add_float!(values, point, heat_index);
-                    let point = add_float!(values, point, precip_rate);
-                    let point = add_float!(values, point, precip_total);
-                    let point = add_float!(values, point, pressure);
-                    let point = add_float!(values, point, temp);
-                    let point = add_float!(values, point, wind_chill);
-                    let point = add_float!(values, point, wind_gust);
-                    add_float!(values, point, wind_speed)
-                },
-                None => point,
-            };
-            match client.write_point(point, None, None).await {
-                Ok(_) => info!("published for {}", station_id),
-                Err(err) => error!("error: {}", err),
-            };
+    for obs in observations.iter() {
+        let point = build_point(settings, name.as_str(), obs);
+        match client
+            .write_point(point, Some(influxdb::Precision::Seconds), None)
+            .await
+        {
+            Ok(_) => info!("published for {}", station_id),
+            Err(err) => error!("error: {}", err),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    fn test_settings() -> Settings {
+        Settings {
+            stations: vec!["KSEA1".into()],
+            station_intervals: HashMap::new(),
+            timeout: Duration::from_secs(1),
+            interval: Duration::from_secs(60),
+            unit: wu::Unit::Metric,
+            influxdb_host: "http://localhost:8086".into(),
+            influxdb_username: "username".into(),
+            influxdb_password: "password".into(),
+            influxdb_database: "default".into(),
+        }
+    }
+
+    fn observation_with_epoch(epoch: u64) -> wu::Observation {
+        wu::Observation {
+            country: "US".into(),
+            epoch,
+            humidity: None,
+            lat: 47.6,
+            lon: -122.3,
+            imperial: None,
+            metric: None,
+            neighborhood: "Downtown".into(),
+            obs_time_local: "2021-01-01 00:00:00".into(),
+            obs_time_utc: "2021-01-01T00:00:00Z".into(),
+            solar_radiation: None,
+            uv: None,
+            winddir: None,
+            condition: None,
         }
     }
+
+    #[test]
+    fn build_point_uses_epoch_seconds_as_timestamp() {
+        let settings = test_settings();
+        let obs = observation_with_epoch(1_600_000_000);
+        let point = build_point(&settings, "test", &obs);
+        let debug = format!("{:?}", point);
+        assert!(
+            debug.contains("1600000000"),
+            "expected point debug output to contain the epoch-seconds timestamp, got {}",
+            debug
+        );
+    }
 }
@@ -1,13 +1,29 @@
 #[macro_use] extern crate log;
 
+mod config;
 mod influx;
 mod server;
 mod settings;
 
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
 #[tokio::main]
 async fn main() {
     env_logger::init();
 
-    let settings = settings::Settings::default();
-    server::run(&settings).await;
+    let config_path = std::env::var("WU_CONFIG").ok().map(PathBuf::from);
+
+    let settings = match &config_path {
+        Some(path) => config::load(path).expect("unable to load config"),
+        None => settings::Settings::default(),
+    };
+    let settings = Arc::new(RwLock::new(settings));
+
+    if let Some(path) = config_path {
+        tokio::spawn(config::watch(path, settings.clone()));
+    }
+
+    server::run(settings).await;
 }
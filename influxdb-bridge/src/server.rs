@@ -1,50 +1,81 @@
 use crate::influx;
 use crate::settings::Settings;
-use reqwest;
+use futures::future::join_all;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::TryFrom;
-use tokio::time::delay_for;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::time::{sleep_until, Instant};
 use weather_underground as wu;
 
+/// Reconcile `schedule` with `settings`, rescheduling any added, removed, or
+/// re-intervaled station. Returns the interval each wanted station now runs on.
+fn reconcile(
+    schedule: &mut BTreeMap<Instant, Vec<String>>,
+    known: &HashMap<String, Duration>,
+    settings: &Settings,
+) -> HashMap<String, Duration> {
+    let wanted: HashSet<String> = settings.stations.iter().cloned().collect();
+
+    let due_now: HashSet<String> = wanted
+        .iter()
+        .filter(|station_id| known.get(*station_id) != Some(&settings.interval_for(station_id)))
+        .cloned()
+        .collect();
+
+    if !due_now.is_empty() || known.keys().any(|id| !wanted.contains(id)) {
+        for stations in schedule.values_mut() {
+            stations.retain(|id| wanted.contains(id) && !due_now.contains(id));
+        }
+        schedule.retain(|_, stations| !stations.is_empty());
+        if !due_now.is_empty() {
+            schedule
+                .entry(Instant::now())
+                .or_insert_with(Vec::new)
+                .extend(due_now);
+        }
+    }
+
+    wanted
+        .into_iter()
+        .map(|id| {
+            let interval = settings.interval_for(&id);
+            (id, interval)
+        })
+        .collect()
+}
+
 #[derive(Debug)]
 pub enum Error {
-    ApiKeyNotFound,
     TooManyRetry,
 }
 
-#[derive(Default)]
 struct Server {
-    api_key: String,
+    client: wu::Client,
 }
 
 impl Server {
-    async fn get_api_key(&mut self, client: &reqwest::Client) -> Result<&str, Error> {
-        if self.api_key.is_empty() {
-            self.api_key = match wu::fetch_api_key(client).await {
-                Ok(value) => value,
-                Err(_) => return Err(Error::ApiKeyNotFound),
-            };
-        }
-        Ok(self.api_key.as_str())
-    }
-
     async fn process(
-        &mut self,
-        client: &reqwest::Client,
+        client: &wu::Client,
         settings: &Settings,
         station_id: &str,
         retry: usize,
     ) -> Result<(), Error> {
         debug!("processing station {}", station_id);
-        let api_key = self.get_api_key(client).await?;
+        let mut client = client.clone();
+        let args = wu::ObservationArgs {
+            unit: settings.unit.clone(),
+            ..Default::default()
+        };
         for _idx in (0..retry).rev() {
-            let result =
-                match wu::fetch_observation(client, api_key, station_id, &settings.unit).await {
-                    Err(err) => {
-                        error!("couldn't fetch observation: {:?}", err);
-                        continue;
-                    }
-                    Ok(value) => value,
-                };
+            let result = match client.fetch_observation_raw(station_id, &args).await {
+                Err(err) => {
+                    error!("couldn't fetch observation: {:?}", err);
+                    continue;
+                }
+                Ok(value) => value,
+            };
             let result = match result {
                 Some(value) => value,
                 None => return Ok(()),
@@ -64,41 +95,155 @@ impl Server {
         Err(Error::TooManyRetry)
     }
 
-    async fn iterate(
+    pub async fn run(
         &mut self,
-        client: &reqwest::Client,
-        settings: &Settings,
+        settings: Arc<RwLock<Settings>>,
     ) -> Result<(), Error> {
-        debug!("iteration");
-        for station_id in settings.stations.iter() {
-            self.process(client, settings, station_id.as_str(), 10)
-                .await?;
+        // Run queue: next scheduled fetch time -> station IDs due then.
+        let mut schedule: BTreeMap<Instant, Vec<String>> = BTreeMap::new();
+        let mut known: HashMap<String, Duration> = HashMap::new();
+
+        loop {
+            // Pick up stations added, removed, or rescheduled (interval
+            // changed) in a reloaded config.
+            let current = settings.read().await.clone();
+            known = reconcile(&mut schedule, &known, &current);
+
+            let next = match schedule.keys().next() {
+                Some(next) => *next,
+                None => {
+                    sleep_until(Instant::now() + current.interval).await;
+                    continue;
+                }
+            };
+
+            if next > Instant::now() {
+                sleep_until(next).await;
+                continue;
+            }
+
+            let due = schedule.remove(&next).expect("key was just peeked");
+
+            let results = join_all(due.iter().map(|station_id| {
+                Self::process(&self.client, &current, station_id.as_str(), 10)
+            }))
+            .await;
+
+            for (station_id, result) in due.iter().zip(results) {
+                if let Err(err) = result {
+                    error!("station {} failed: {:?}", station_id, err);
+                }
+            }
+
+            let now = Instant::now();
+            for station_id in due {
+                let next_run = now + current.interval_for(station_id.as_str());
+                schedule.entry(next_run).or_insert_with(Vec::new).push(station_id);
+            }
         }
-        info!("iteration done");
-        Ok(())
     }
+}
 
-    async fn sleep(&self, settings: &Settings) {
-        debug!("sleeping for {:?}", settings.interval);
-        delay_for(settings.interval).await;
-    }
+/// Pull the hourly and daily history for every station and write it to InfluxDB.
+async fn backfill(client: &wu::Client, settings: &Settings) {
+    let mut client = client.clone();
+    let args = wu::ObservationArgs {
+        unit: settings.unit.clone(),
+        ..Default::default()
+    };
 
-    pub async fn run(
-        &mut self,
-        client: &reqwest::Client,
-        settings: &Settings,
-    ) -> Result<(), Error> {
-        loop {
-            self.iterate(client, settings).await?;
-            self.sleep(settings).await;
+    for station_id in settings.stations.iter() {
+        for history in [wu::History::Hourly, wu::History::Daily] {
+            let label = history.to_string();
+            match client.fetch_history(station_id, history, &args).await {
+                Ok(response) => influx::publish_history(settings, station_id, &response).await,
+                Err(err) => error!(
+                    "unable to backfill {} history for {}: {:?}",
+                    label, station_id, err
+                ),
+            }
         }
     }
 }
 
-pub async fn run(settings: &Settings) {
-    let client = wu::create_client(settings.timeout).expect("unable to create client");
-    let mut srv = Server::default();
-    srv.run(&client, settings)
+pub async fn run(settings: Arc<RwLock<Settings>>) {
+    let timeout = settings.read().await.timeout;
+    let opts = wu::ClientOpts { timeout };
+    let client = wu::Client::create(None, opts)
         .await
-        .expect("something happened");
+        .expect("unable to create client");
+
+    let current = settings.read().await.clone();
+    backfill(&client, &current).await;
+
+    let mut srv = Server { client };
+    srv.run(settings).await.expect("something happened");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings_with(stations: &[&str], interval_ms: u64) -> Settings {
+        Settings {
+            stations: stations.iter().map(|s| s.to_string()).collect(),
+            station_intervals: HashMap::new(),
+            timeout: Duration::from_secs(1),
+            interval: Duration::from_millis(interval_ms),
+            unit: wu::Unit::Metric,
+            influxdb_host: "http://localhost:8086".into(),
+            influxdb_username: "username".into(),
+            influxdb_password: "password".into(),
+            influxdb_database: "default".into(),
+        }
+    }
+
+    #[test]
+    fn reconcile_schedules_new_stations_immediately() {
+        let mut schedule = BTreeMap::new();
+        let settings = settings_with(&["a", "b"], 1000);
+
+        let known = reconcile(&mut schedule, &HashMap::new(), &settings);
+
+        assert_eq!(known.len(), 2);
+        assert_eq!(schedule.values().flatten().count(), 2);
+    }
+
+    #[test]
+    fn reconcile_drops_removed_stations() {
+        let mut schedule = BTreeMap::new();
+        schedule.insert(
+            Instant::now() + Duration::from_secs(60),
+            vec!["a".to_string(), "b".to_string()],
+        );
+        let mut known = HashMap::new();
+        known.insert("a".to_string(), Duration::from_millis(1000));
+        known.insert("b".to_string(), Duration::from_millis(1000));
+
+        let settings = settings_with(&["a"], 1000);
+        let known = reconcile(&mut schedule, &known, &settings);
+
+        assert_eq!(known.len(), 1);
+        assert!(known.contains_key("a"));
+        assert_eq!(
+            schedule.values().flatten().collect::<Vec<_>>(),
+            vec!["a"]
+        );
+    }
+
+    #[test]
+    fn reconcile_reschedules_stations_with_changed_interval() {
+        let mut schedule = BTreeMap::new();
+        let stale_slot = Instant::now() + Duration::from_secs(3600);
+        schedule.insert(stale_slot, vec!["a".to_string()]);
+        let mut known = HashMap::new();
+        known.insert("a".to_string(), Duration::from_millis(60_000));
+
+        let settings = settings_with(&["a"], 1_000);
+        let known = reconcile(&mut schedule, &known, &settings);
+
+        assert_eq!(known.get("a"), Some(&Duration::from_millis(1_000)));
+        assert!(!schedule.contains_key(&stale_slot));
+        assert!(schedule.values().flatten().any(|id| id == "a"));
+    }
 }
@@ -1,9 +1,11 @@
+use std::collections::HashMap;
 use std::time::Duration;
 use weather_underground as wu;
 
 #[derive(Clone)]
 pub struct Settings {
     pub stations: Vec<String>,
+    pub station_intervals: HashMap<String, Duration>,
     pub timeout: Duration,
     pub interval: Duration,
     pub unit: wu::Unit,
@@ -15,8 +17,10 @@ pub struct Settings {
 
 impl Default for Settings {
     fn default() -> Self {
+        let (stations, station_intervals) = Self::read_stations();
         Self {
-            stations: Self::read_stations(),
+            stations,
+            station_intervals,
             timeout: Self::read_timeout(),
             interval: Self::read_interval(),
             unit: wu::Unit::Metric,
@@ -44,16 +48,31 @@ impl Settings {
         }
     }
 
-    fn read_stations() -> Vec<String> {
+    /// Parse `WU_STATIONS` into station IDs and optional per-station intervals.
+    fn read_stations() -> (Vec<String>, HashMap<String, Duration>) {
         let value = match std::env::var("WU_STATIONS") {
-            Ok(value) => value.split(",").map(|v| v.into()).collect::<Vec<String>>(),
+            Ok(value) => value,
             Err(_) => panic!("unable to parse WU_STATIONS"),
         };
-        if value.is_empty() {
+
+        let mut stations = Vec::new();
+        let mut intervals = HashMap::new();
+        for entry in value.split(",") {
+            let (station_id, interval) = match parse_station_entry(entry) {
+                Ok(value) => value,
+                Err(err) => panic!("{}", err),
+            };
+            if let Some(interval) = interval {
+                intervals.insert(station_id.clone(), interval);
+            }
+            stations.push(station_id);
+        }
+
+        if stations.is_empty() {
             panic!("WU_STATIONS shouldn't be empty")
-        } else {
-            value
         }
+
+        (stations, intervals)
     }
 
     fn read_timeout() -> Duration {
@@ -66,4 +85,26 @@ impl Settings {
             Err(_) => panic!("unable to parse WU_TIMEOUT"),
         }
     }
+
+    /// Polling interval for a station, falling back to `interval`.
+    pub fn interval_for(&self, station_id: &str) -> Duration {
+        self.station_intervals
+            .get(station_id)
+            .copied()
+            .unwrap_or(self.interval)
+    }
+}
+
+/// Parse a `WU_STATIONS`/config `stations` entry of the form
+/// `ID` or `ID:INTERVAL_MS` into a station ID and optional interval.
+pub(crate) fn parse_station_entry(entry: &str) -> Result<(String, Option<Duration>), String> {
+    match entry.split_once(":") {
+        Some((station_id, interval)) => {
+            let interval = interval
+                .parse::<u64>()
+                .map_err(|_| format!("invalid interval for station {}", station_id))?;
+            Ok((station_id.to_string(), Some(Duration::from_millis(interval))))
+        }
+        None => Ok((entry.to_string(), None)),
+    }
 }
@@ -9,6 +9,7 @@ use std::time::Duration;
 use std::convert::TryFrom;
 
 use strum_macros::{Display, EnumString};
+use tokio::sync::mpsc;
 
 lazy_static! {
     static ref API_KEY_REGEX: Regex = Regex::new(r"apiKey=([a-z0-9]+)").unwrap();
@@ -39,6 +40,16 @@ pub struct ObservationValue {
     pub wind_speed: Option<f64>,
 }
 
+/// Qualitative sky condition accompanying an observation (e.g. rain, clear).
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Condition {
+    pub condition_code: Option<i64>,
+    pub main: Option<String>,
+    pub description: Option<String>,
+    pub icon: Option<String>,
+}
+
 /// Object that represents an observation
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -56,6 +67,7 @@ pub struct Observation {
     pub solar_radiation: Option<f64>,
     pub uv: Option<f64>,
     pub winddir: Option<f64>,
+    pub condition: Option<Condition>,
 }
 
 impl Observation {
@@ -93,6 +105,82 @@ impl TryFrom<serde_json::Value> for ObservationResponse {
     }
 }
 
+/// Object returned by the weather underground API when fetching history
+#[derive(Debug, Deserialize, Serialize)]
+pub struct HistoryResponse {
+    pub observations: Vec<Observation>,
+}
+
+impl TryFrom<serde_json::Value> for HistoryResponse {
+    type Error = serde_json::Error;
+
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        serde_json::from_value(value)
+    }
+}
+
+/// A nearby personal weather station, as returned by the station locator
+#[derive(Debug, Deserialize, Serialize)]
+pub struct StationInfo {
+    pub station_id: String,
+    pub neighborhood: String,
+    pub country: String,
+    pub distance_km: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct StationSearchResponse {
+    location: StationSearchLocation,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StationSearchLocation {
+    station_id: Vec<String>,
+    neighborhood: Vec<String>,
+    country: Vec<String>,
+    distance_km: Vec<f64>,
+}
+
+impl TryFrom<StationSearchLocation> for Vec<StationInfo> {
+    type Error = Error;
+
+    fn try_from(location: StationSearchLocation) -> Result<Self, Self::Error> {
+        let len = location.station_id.len();
+        if location.neighborhood.len() != len
+            || location.country.len() != len
+            || location.distance_km.len() != len
+        {
+            return Err(Error::MismatchedStationFields);
+        }
+
+        Ok(location
+            .station_id
+            .into_iter()
+            .zip(location.neighborhood)
+            .zip(location.country)
+            .zip(location.distance_km)
+            .map(|(((station_id, neighborhood), country), distance_km)| StationInfo {
+                station_id,
+                neighborhood,
+                country,
+                distance_km,
+            })
+            .collect())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct GeocodeResponse {
+    location: GeocodeLocation,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeocodeLocation {
+    latitude: Vec<f64>,
+    longitude: Vec<f64>,
+}
+
 #[derive(Debug, Clone, PartialEq, Display, EnumString)]
 #[strum(serialize_all = "snake_case")]
 pub enum Format {
@@ -125,6 +213,7 @@ pub enum Unit {
 }
 
 
+#[derive(Clone)]
 pub struct ObservationArgs {
     pub format: Format,
     pub unit: Unit,
@@ -168,6 +257,9 @@ impl ObservationArgs {
 pub enum Error {
     ApiKeyNotFound,
     ApiKeyInvalid,
+    NoObservation,
+    PlaceNotFound,
+    MismatchedStationFields,
     Reqwest(reqwest::Error),
     PayloadInvalid(serde_json::Error),
 }
@@ -195,7 +287,7 @@ fn parse_api_key(html: &str) -> Result<String, Error> {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Client {
     api_key: String,
     c: reqwest::Client,
@@ -272,6 +364,145 @@ impl Client {
 
         Ok(Some(body))
     }
+
+    /// Fetch a historical observation series (hourly, daily, or all) for a station.
+    pub async fn fetch_history(
+        &mut self,
+        station_id: &str,
+        history: History,
+        args: &ObservationArgs,
+    ) -> Result<HistoryResponse, Error> {
+        debug!("fetching {} history for station {}", history, station_id);
+
+        let mut args = args.clone();
+        args.history = history;
+
+        let url = format!(
+            "https://api.weather.com/v2/pws/observations/{}",
+            args.build_query(&self.api_key, station_id)
+        );
+
+        let response = self
+            .c
+            .get(url.as_str())
+            .header("Accept-Encoding", "gzip")
+            .send()
+            .await?
+            .json()
+            .await?;
+
+        Ok(HistoryResponse::try_from(response)?)
+    }
+
+    /// Poll `station_ids` on `interval`, forwarding each observation (or
+    /// error) down the returned channel until the receiver is dropped.
+    pub fn subscribe(
+        &self,
+        station_ids: Vec<String>,
+        args: ObservationArgs,
+        interval: Duration,
+    ) -> mpsc::Receiver<Result<Observation, Error>> {
+        let (tx, rx) = mpsc::channel(16);
+        let client = self.clone();
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+
+                for station_id in &station_ids {
+                    let result = client.poll_one(station_id.as_str(), &args).await;
+                    let result = match result {
+                        Ok(None) => continue,
+                        Ok(Some(observation)) => Ok(observation),
+                        Err(err) => Err(err),
+                    };
+                    if tx.send(result).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        rx
+    }
+
+    /// Find personal weather stations within `radius_km` of a coordinate.
+    pub async fn find_stations(
+        &mut self,
+        lat: f64,
+        lon: f64,
+        radius_km: f64,
+    ) -> Result<Vec<StationInfo>, Error> {
+        debug!("finding stations near {},{} within {}km", lat, lon, radius_km);
+
+        let url = format!(
+            "https://api.weather.com/v3/location/near?geocode={},{}&product=pws&radius={}&format=json&apiKey={}",
+            lat, lon, radius_km, self.api_key,
+        );
+
+        let response: StationSearchResponse = self
+            .c
+            .get(url.as_str())
+            .header("Accept-Encoding", "gzip")
+            .send()
+            .await?
+            .json()
+            .await?;
+
+        Vec::<StationInfo>::try_from(response.location)
+    }
+
+    /// Geocode `query` (e.g. a city name) and find nearby stations.
+    pub async fn find_stations_near_place(
+        &mut self,
+        query: &str,
+        radius_km: f64,
+    ) -> Result<Vec<StationInfo>, Error> {
+        let (lat, lon) = self.geocode(query).await?;
+        self.find_stations(lat, lon, radius_km).await
+    }
+
+    async fn geocode(&mut self, query: &str) -> Result<(f64, f64), Error> {
+        debug!("geocoding place {}", query);
+
+        let url = format!(
+            "https://api.weather.com/v3/location/search?query={}&locationType=city&format=json&apiKey={}",
+            query, self.api_key,
+        );
+
+        let response: GeocodeResponse = self.c.get(url.as_str()).send().await?.json().await?;
+
+        match (
+            response.location.latitude.first(),
+            response.location.longitude.first(),
+        ) {
+            (Some(lat), Some(lon)) => Ok((*lat, *lon)),
+            _ => Err(Error::PlaceNotFound),
+        }
+    }
+
+    async fn poll_one(
+        &self,
+        station_id: &str,
+        args: &ObservationArgs,
+    ) -> Result<Option<Observation>, Error> {
+        let mut client = self.clone();
+        let body = match client.fetch_observation_raw(station_id, args).await? {
+            Some(body) => body,
+            None => return Ok(None),
+        };
+        first_observation(body).map(Some)
+    }
+}
+
+/// Pull the first observation out of a raw `fetch_observation_raw` body.
+fn first_observation(body: serde_json::Value) -> Result<Observation, Error> {
+    let mut response = ObservationResponse::try_from(body)?;
+    match response.observations.take() {
+        Some(mut observations) if !observations.is_empty() => Ok(observations.remove(0)),
+        _ => Err(Error::NoObservation),
+    }
 }
 
 
@@ -309,4 +540,111 @@ mod tests {
         let result = result.observations.unwrap();
         assert_eq!(result.len(), 1);
     }
+
+    fn observation_json(condition: serde_json::Value) -> serde_json::Value {
+        serde_json::json!({
+            "observations": [{
+                "country": "US",
+                "epoch": 1_600_000_000,
+                "humidity": 50.0,
+                "lat": 47.6,
+                "lon": -122.3,
+                "neighborhood": "Downtown",
+                "obsTimeLocal": "2021-01-01 00:00:00",
+                "obsTimeUtc": "2021-01-01T00:00:00Z",
+                "metric": {},
+                "condition": condition,
+            }]
+        })
+    }
+
+    #[test]
+    fn parsing_condition() {
+        let body = observation_json(serde_json::json!({
+            "conditionCode": 4200,
+            "main": "Rain",
+            "description": "Light Rain",
+            "icon": "rain",
+        }));
+        let response = ObservationResponse::try_from(body).unwrap();
+        let observation = &response.observations.unwrap()[0];
+        let condition = observation.condition.as_ref().unwrap();
+        assert_eq!(condition.condition_code, Some(4200));
+        assert_eq!(condition.main.as_deref(), Some("Rain"));
+        assert_eq!(condition.description.as_deref(), Some("Light Rain"));
+    }
+
+    #[test]
+    fn parsing_history_response() {
+        let body = serde_json::json!({
+            "observations": [{
+                "country": "US",
+                "epoch": 1_600_000_000,
+                "humidity": null,
+                "lat": 47.6,
+                "lon": -122.3,
+                "neighborhood": "Downtown",
+                "obsTimeLocal": "2021-01-01 00:00:00",
+                "obsTimeUtc": "2021-01-01T00:00:00Z",
+                "metric": null,
+            }]
+        });
+        let response = HistoryResponse::try_from(body).unwrap();
+        assert_eq!(response.observations.len(), 1);
+        assert_eq!(response.observations[0].epoch, 1_600_000_000);
+    }
+
+    #[test]
+    fn parsing_station_search_response() {
+        let body = serde_json::json!({
+            "location": {
+                "stationId": ["KSEA1"],
+                "neighborhood": ["Downtown"],
+                "country": ["US"],
+                "distanceKm": [1.5],
+            }
+        });
+        let response: StationSearchResponse = serde_json::from_value(body).unwrap();
+        assert_eq!(response.location.station_id, vec!["KSEA1".to_string()]);
+        assert_eq!(response.location.distance_km, vec![1.5]);
+    }
+
+    #[test]
+    fn station_search_location_converts_to_station_info() {
+        let location = StationSearchLocation {
+            station_id: vec!["KSEA1".into()],
+            neighborhood: vec!["Downtown".into()],
+            country: vec!["US".into()],
+            distance_km: vec![1.5],
+        };
+        let stations = Vec::<StationInfo>::try_from(location).unwrap();
+        assert_eq!(stations.len(), 1);
+        assert_eq!(stations[0].station_id, "KSEA1");
+    }
+
+    #[test]
+    fn station_search_location_rejects_mismatched_lengths() {
+        let location = StationSearchLocation {
+            station_id: vec!["KSEA1".into(), "KSEA2".into()],
+            neighborhood: vec!["Downtown".into()],
+            country: vec!["US".into()],
+            distance_km: vec![1.5],
+        };
+        let result = Vec::<StationInfo>::try_from(location);
+        assert!(matches!(result, Err(Error::MismatchedStationFields)));
+    }
+
+    #[test]
+    fn first_observation_returns_first_entry() {
+        let body = observation_json(serde_json::Value::Null);
+        let observation = first_observation(body).unwrap();
+        assert_eq!(observation.country, "US");
+    }
+
+    #[test]
+    fn first_observation_errors_when_empty() {
+        let body = serde_json::json!({ "observations": [] });
+        let result = first_observation(body);
+        assert!(matches!(result, Err(Error::NoObservation)));
+    }
 }
\ No newline at end of file